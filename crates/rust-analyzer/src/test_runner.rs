@@ -4,6 +4,7 @@
 use crossbeam_channel::Sender;
 use paths::AbsPath;
 use project_model::TargetKind;
+use rustc_hash::FxHashMap;
 use serde::Deserialize as _;
 use serde_derive::Deserialize;
 use toolchain::Tool;
@@ -17,15 +18,112 @@ use crate::{
 #[serde(tag = "event", rename_all = "camelCase")]
 pub(crate) enum TestState {
     Started,
-    Ok,
+    Ok {
+        // not always present: older libtest and some nextest versions omit it
+        #[serde(default)]
+        exec_time: Option<f64>,
+    },
     Ignored,
     Failed {
         // the stdout field is not always present depending on cargo test flags
         #[serde(skip_serializing_if = "String::is_empty", default)]
         stdout: String,
+        // not always present: older libtest and some nextest versions omit it
+        #[serde(default)]
+        exec_time: Option<f64>,
+        // populated from `stdout` by `TestFailure::parse` once deserialization is done,
+        // see `CargoTestMessage::from_line`
+        #[serde(skip)]
+        failure: TestFailure,
+    },
+    /// Not produced by deserialization: synthesized by [`FlakyAggregator`] when a nextest
+    /// retry (`--retries`) eventually passes a test that failed on an earlier attempt.
+    #[serde(skip)]
+    Flaky {
+        passed_on_attempt: u32,
+        total_attempts: u32,
     },
 }
 
+/// A [`TestState::Failed`] failure, pulled out of the raw captured stdout so the editor
+/// can anchor a diagnostic at the panic site instead of just dumping text.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct TestFailure {
+    pub(crate) message: String,
+    // `file!()`-style path as printed by libtest, i.e. workspace-relative, not absolute
+    pub(crate) location: Option<(String, u32, u32)>,
+    pub(crate) left_right: Option<(String, String)>,
+}
+
+impl TestFailure {
+    /// Scans captured test stdout for the standard libtest panic header
+    /// (`thread '...' panicked at <file>:<line>:<col>:`) and, if present, an
+    /// `assertion ``left == right`` failed` block's `left:`/`right:` values. Only the
+    /// first panic is considered: later ones (e.g. from a spawned thread during unwind)
+    /// aren't what actually failed the test.
+    fn parse(stdout: &str) -> Self {
+        let mut location = None;
+        let mut left_right = None;
+        let mut left = None;
+        let mut message_lines = Vec::new();
+        let mut in_message = false;
+        let mut seen_header = false;
+
+        for line in stdout.lines() {
+            // lines like `thread 'io-worker' waiting...` also start with `thread '` but
+            // aren't a panic header, so only treat it as one once `panicked at ` is found
+            if let Some(rest) =
+                line.trim_start().strip_prefix("thread '").and_then(|r| r.split("panicked at ").nth(1))
+            {
+                if seen_header {
+                    break;
+                }
+                seen_header = true;
+                in_message = true;
+
+                let rest = rest.strip_suffix(':').unwrap_or(rest);
+                let mut parts = rest.rsplitn(3, ':');
+                if let (Some(col), Some(row), Some(file)) =
+                    (parts.next(), parts.next(), parts.next())
+                {
+                    if let (Ok(row), Ok(col)) = (row.parse(), col.parse()) {
+                        location = Some((file.to_owned(), row, col));
+                    }
+                }
+                continue;
+            }
+
+            if !in_message {
+                continue;
+            }
+
+            let trimmed = line.trim();
+            // the message block ends at the first blank line or the backtrace hint
+            if trimmed.is_empty() || trimmed.starts_with("note:") {
+                break;
+            }
+            if let Some(value) = trimmed.strip_prefix("left:") {
+                left = Some(value.trim().to_owned());
+            } else if let Some(value) = trimmed.strip_prefix("right:") {
+                if let Some(left) = left.take() {
+                    left_right = Some((left, value.trim().to_owned()));
+                }
+            } else {
+                message_lines.push(trimmed);
+            }
+        }
+
+        TestFailure { message: message_lines.join("\n"), location, left_right }
+    }
+
+    /// Joins the workspace-relative panic location against `root`, the only place that
+    /// knows where the workspace actually lives.
+    pub(crate) fn resolved_location(&self, root: &AbsPath) -> Option<(paths::AbsPathBuf, u32, u32)> {
+        let (file, row, col) = self.location.as_ref()?;
+        Some((root.join(file), *row, *col))
+    }
+}
+
 #[derive(Debug, Deserialize)]
 #[serde(tag = "type", rename_all = "camelCase")]
 pub(crate) enum CargoTestMessage {
@@ -36,19 +134,117 @@ pub(crate) enum CargoTestMessage {
     },
     Suite,
     Finished,
+    /// Not produced by the `#[serde(tag = "type")]` deserialization above: synthesized by
+    /// [`ParseFromLine::from_line`] from the differently-shaped JSON emitted by
+    /// `cargo nextest list`/`cargo test -- --list`, see [`CargoTestHandle::discover`].
+    ///
+    /// `cargo nextest list` prints its entire summary as one JSON object, so it produces a
+    /// single `Discovered` carrying every suite. `cargo test -- --list` instead prints one
+    /// JSON object per test with no suite grouping, so on that backend `Discovered` arrives
+    /// once per test (each under the synthetic `"workspace"` suite) — the same
+    /// one-message-per-line streaming `CargoTestMessage::Test` already uses. Consumers must
+    /// accumulate `Discovered` messages by suite name rather than assume one covers the
+    /// whole tree.
+    #[serde(skip)]
+    Discovered { suites: Vec<(String, Vec<DiscoveredTest>)> },
+    /// Not produced by deserialization: synthesized by
+    /// [`CargoTestHandle::generate_coverage_report`].
+    #[serde(skip)]
+    Coverage {
+        file: paths::AbsPathBuf,
+        // (line number, hit count)
+        lines: Vec<(u32, u64)>,
+    },
     Custom {
         text: String,
     },
 }
 
+/// A single test found by `cargo nextest list`/`cargo test -- --list`, before it has
+/// ever been run.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct DiscoveredTest {
+    pub(crate) name: String,
+    pub(crate) ignored: bool,
+    // raw kind string from the list JSON ("lib" | "bin" | "test" | "bench", ...)
+    pub(crate) kind: String,
+}
+
+// Shape of `cargo nextest list --message-format json`'s summary object:
+// `{ "rust-suites": { "<binary-id>": { "binary-path": ..., "package-id": ..., "kind": ...,
+// "testcases": { "<name>": { "ignored": bool, "filter-match": {...} } } } } }`
+#[derive(Debug, Deserialize)]
+struct NextestListSummary {
+    #[serde(rename = "rust-suites")]
+    rust_suites: FxHashMap<String, NextestListSuite>,
+}
+
+#[derive(Debug, Deserialize)]
+struct NextestListSuite {
+    #[expect(dead_code, reason = "not surfaced yet, kept for parity with the list JSON")]
+    #[serde(rename = "binary-path")]
+    binary_path: String,
+    #[expect(dead_code, reason = "not surfaced yet, kept for parity with the list JSON")]
+    #[serde(rename = "package-id")]
+    package_id: String,
+    kind: String,
+    testcases: FxHashMap<String, NextestListTestcase>,
+}
+
+#[derive(Debug, Deserialize)]
+struct NextestListTestcase {
+    ignored: bool,
+}
+
 impl ParseFromLine for CargoTestMessage {
     fn from_line(line: &str, _: &mut String) -> Option<Self> {
         let mut deserializer = serde_json::Deserializer::from_str(line);
         deserializer.disable_recursion_limit();
-        if let Ok(message) = CargoTestMessage::deserialize(&mut deserializer) {
+        if let Ok(mut message) = CargoTestMessage::deserialize(&mut deserializer) {
+            if let CargoTestMessage::Test {
+                state: TestState::Failed { stdout, failure, .. },
+                ..
+            } = &mut message
+            {
+                *failure = TestFailure::parse(stdout);
+            }
             return Some(message);
         }
 
+        // `cargo nextest list --message-format json` prints its whole summary as a single
+        // JSON object rather than the `{"type": ...}`-tagged events matched above, so it
+        // falls through to here. Unlike the rest of this function, one line can describe
+        // several suites at once — see the doc comment on `Discovered`.
+        if let Ok(summary) = serde_json::from_str::<NextestListSummary>(line) {
+            let suites = summary
+                .rust_suites
+                .into_iter()
+                .map(|(suite, info)| {
+                    let kind = info.kind;
+                    let tests = info
+                        .testcases
+                        .into_iter()
+                        .map(|(name, testcase)| DiscoveredTest {
+                            name,
+                            ignored: testcase.ignored,
+                            kind: kind.clone(),
+                        })
+                        .collect();
+                    (suite, tests)
+                })
+                .collect();
+            return Some(CargoTestMessage::Discovered { suites });
+        }
+
+        // libtest's `--list --format=json` prints one JSON object per test, with no
+        // binary/suite grouping, so each line becomes its own single-test `Discovered`.
+        if let Ok(entry) = serde_json::from_str::<LibtestListEntry>(line) {
+            let test = DiscoveredTest { name: entry.name, ignored: entry.ignore, kind: "test".to_owned() };
+            return Some(CargoTestMessage::Discovered {
+                suites: vec![("workspace".to_owned(), vec![test])],
+            });
+        }
+
         Some(CargoTestMessage::Custom { text: line.to_owned() })
     }
 
@@ -76,6 +272,63 @@ pub(crate) enum TestTarget {
 pub(crate) enum TestToolKind {
     CargoTest,
     CargoNextest,
+    /// Runs the suite under `cargo llvm-cov nextest` so a line-coverage report can be
+    /// generated from it afterwards, see [`CargoTestHandle::generate_coverage_report`].
+    CargoLlvmCov,
+}
+
+/// Aggregates consecutive nextest retry (`--retries`) attempts for the same test name.
+/// Nextest emits one `ok`/`failed` event per attempt under the *same* test name, so the
+/// consumer reading off the result channel is expected to route every
+/// `CargoTestMessage::Test` through [`Self::observe`], and to call [`Self::flush`] once
+/// [`CargoTestMessage::Finished`] arrives to pick up tests that failed on every attempt.
+///
+/// The bare test name is not unique across a workspace (nextest's libtest-json `name`
+/// field isn't binary-qualified), so every entry is keyed on `(suite, name)` — pass the
+/// binary-id/suite the test belongs to (the same one reported in `CargoTestMessage::Discovered`),
+/// or any other identifier that's unique per running suite if discovery wasn't run first.
+#[derive(Debug, Default)]
+pub(crate) struct FlakyAggregator {
+    // (suite, test name) -> (failed attempts seen so far, most recent `Failed` message)
+    pending: FxHashMap<(String, String), (u32, CargoTestMessage)>,
+}
+
+impl FlakyAggregator {
+    /// Returns `Some` with the message to forward right away, or `None` if the message
+    /// was a failed attempt that might still turn out to be flaky.
+    pub(crate) fn observe(
+        &mut self,
+        suite: &str,
+        message: CargoTestMessage,
+    ) -> Option<CargoTestMessage> {
+        match &message {
+            CargoTestMessage::Test { name, state: TestState::Failed { .. } } => {
+                let key = (suite.to_owned(), name.clone());
+                let attempts = self.pending.get(&key).map_or(0, |(attempts, _)| *attempts) + 1;
+                self.pending.insert(key, (attempts, message));
+                None
+            }
+            CargoTestMessage::Test { name, state: TestState::Ok { .. } } => {
+                let key = (suite.to_owned(), name.clone());
+                match self.pending.remove(&key) {
+                    Some((attempts, _)) => Some(CargoTestMessage::Test {
+                        name: key.1,
+                        state: TestState::Flaky {
+                            passed_on_attempt: attempts + 1,
+                            total_attempts: attempts + 1,
+                        },
+                    }),
+                    None => Some(message),
+                }
+            }
+            _ => Some(message),
+        }
+    }
+
+    /// Drains the tests that failed on every attempt (no later retry passed).
+    pub(crate) fn flush(&mut self) -> Vec<CargoTestMessage> {
+        self.pending.drain().map(|(_, (_, message))| message).collect()
+    }
 }
 
 impl CargoTestHandle {
@@ -85,6 +338,7 @@ impl CargoTestHandle {
         options: CargoOptions,
         root: &AbsPath,
         test_target: TestTarget,
+        retries: Option<u32>,
         sender: Sender<CargoTestMessage>,
     ) -> std::io::Result<Self> {
         let mut cmd = toolchain::command(Tool::Cargo.path(), root);
@@ -125,6 +379,26 @@ impl CargoTestHandle {
                     cmd.arg("-E");
                     cmd.arg(dsl);
                 }
+                if let Some(retries) = retries {
+                    cmd.arg("--retries");
+                    cmd.arg(retries.to_string());
+                }
+            }
+            TestToolKind::CargoLlvmCov => {
+                cmd.env("NEXTEST_EXPERIMENTAL_LIBTEST_JSON", "1");
+                cmd.arg("llvm-cov");
+                cmd.arg("nextest");
+                // don't generate the report yet, `generate_coverage_report` does that once
+                // the instrumented run has finished
+                cmd.arg("--no-report");
+                if let Some(dsl) = path {
+                    cmd.arg("-E");
+                    cmd.arg(dsl);
+                }
+                if let Some(retries) = retries {
+                    cmd.arg("--retries");
+                    cmd.arg(retries.to_string());
+                }
             }
         }
 
@@ -147,7 +421,7 @@ impl CargoTestHandle {
                     cmd.arg(extra_arg);
                 }
             }
-            TestToolKind::CargoNextest => {
+            TestToolKind::CargoNextest | TestToolKind::CargoLlvmCov => {
                 cmd.arg("--message-format");
                 cmd.arg("libtest-json");
                 cmd.arg("--");
@@ -159,4 +433,367 @@ impl CargoTestHandle {
 
         Ok(Self { _handle: CommandHandle::spawn(cmd, sender)? })
     }
+
+    /// Discovers the full test tree without running anything, by spawning
+    /// `cargo nextest list --message-format json` (or libtest's `--list --format=json`
+    /// for the plain `cargo test` backend) through [`CommandHandle`], same as [`Self::new`].
+    /// [`ParseFromLine::from_line`] turns the list output into [`CargoTestMessage::Discovered`]
+    /// — on the `cargo test` backend this arrives as one `Discovered` per test rather than
+    /// one for the whole tree, see the doc comment on that variant.
+    pub(crate) fn discover(
+        test_tool: TestToolKind,
+        options: CargoOptions,
+        root: &AbsPath,
+        test_target: TestTarget,
+        sender: Sender<CargoTestMessage>,
+    ) -> std::io::Result<Self> {
+        let mut cmd = toolchain::command(Tool::Cargo.path(), root);
+        cmd.env("RUSTC_BOOTSTRAP", "1");
+
+        match test_tool {
+            TestToolKind::CargoTest => cmd.arg("test"),
+            // instrumentation is irrelevant for just listing tests, so fall back to plain
+            // nextest here rather than paying for a `cargo llvm-cov` wrapper
+            TestToolKind::CargoNextest | TestToolKind::CargoLlvmCov => {
+                cmd.arg("nextest");
+                cmd.arg("list")
+            }
+        };
+
+        match &test_target {
+            TestTarget::Package { package, target, kind } => {
+                cmd.arg("--package");
+                cmd.arg(package);
+                match kind {
+                    TargetKind::Lib { .. } => {
+                        cmd.arg("--lib");
+                    }
+                    TargetKind::Other => {}
+                    _ => {
+                        cmd.arg(format!("--{kind}"));
+                        cmd.arg(target);
+                    }
+                }
+            }
+            TestTarget::Workspace => {
+                cmd.arg("--workspace");
+            }
+        }
+
+        cmd.arg("--manifest-path");
+        cmd.arg(root.join("Cargo.toml"));
+        options.apply_on_command(&mut cmd);
+
+        match test_tool {
+            TestToolKind::CargoTest => {
+                cmd.arg("--");
+                cmd.args(["--list", "--format=json", "-Z", "unstable-options"]);
+            }
+            TestToolKind::CargoNextest | TestToolKind::CargoLlvmCov => {
+                cmd.arg("--message-format");
+                cmd.arg("json");
+            }
+        }
+
+        Ok(Self { _handle: CommandHandle::spawn(cmd, sender)? })
+    }
+
+    /// Turns a just-finished `CargoLlvmCov` run into per-line coverage data: runs
+    /// `cargo llvm-cov report --lcov --output-path <tmp>` and sends one
+    /// [`CargoTestMessage::Coverage`] per source file recorded in the LCOV output.
+    pub(crate) fn generate_coverage_report(
+        root: &AbsPath,
+        sender: &Sender<CargoTestMessage>,
+    ) -> std::io::Result<()> {
+        // `std::process::id()` alone isn't enough to make this unique: rust-analyzer is one
+        // long-lived process, so two coverage runs started before the first finishes would
+        // otherwise read/write/delete the same file out from under each other.
+        static NEXT_RUN_ID: std::sync::atomic::AtomicU64 = std::sync::atomic::AtomicU64::new(0);
+        let run_id = NEXT_RUN_ID.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        let lcov_path = std::env::temp_dir()
+            .join(format!("ra-llvm-cov-{}-{run_id}.lcov", std::process::id()));
+
+        let mut cmd = toolchain::command(Tool::Cargo.path(), root);
+        cmd.env("RUSTC_BOOTSTRAP", "1");
+        cmd.arg("llvm-cov");
+        cmd.arg("report");
+        cmd.arg("--lcov");
+        cmd.arg("--output-path");
+        cmd.arg(&lcov_path);
+        cmd.arg("--manifest-path");
+        cmd.arg(root.join("Cargo.toml"));
+        cmd.output()?;
+
+        let lcov = std::fs::read_to_string(&lcov_path)?;
+        let _ = std::fs::remove_file(&lcov_path);
+
+        for (file, lines) in parse_lcov(&lcov) {
+            let Ok(file) = paths::AbsPathBuf::try_from(file) else { continue };
+            sender.send(CargoTestMessage::Coverage { file, lines }).ok();
+        }
+
+        Ok(())
+    }
+}
+
+/// Reads the `SF:<path>` and `DA:<line>,<count>` records out of an LCOV tracefile,
+/// grouping hit counts by source file. Other record types (`FN:`, `BRDA:`, ...) are
+/// ignored, we only need line coverage for the gutter indicators.
+fn parse_lcov(lcov: &str) -> Vec<(String, Vec<(u32, u64)>)> {
+    let mut files = Vec::new();
+    let mut current: Option<(String, Vec<(u32, u64)>)> = None;
+
+    for line in lcov.lines() {
+        if let Some(path) = line.strip_prefix("SF:") {
+            if let Some(file) = current.take() {
+                files.push(file);
+            }
+            current = Some((path.to_owned(), Vec::new()));
+        } else if let Some(rest) = line.strip_prefix("DA:") {
+            let Some((line_no, hit_count)) = rest.split_once(',') else { continue };
+            let (Ok(line_no), Ok(hit_count)) = (line_no.parse(), hit_count.parse()) else {
+                continue;
+            };
+            if let Some((_, lines)) = &mut current {
+                lines.push((line_no, hit_count));
+            }
+        } else if line == "end_of_record" {
+            if let Some(file) = current.take() {
+                files.push(file);
+            }
+        }
+    }
+    if let Some(file) = current.take() {
+        files.push(file);
+    }
+
+    files
+}
+
+#[derive(Debug, Deserialize)]
+struct LibtestListEntry {
+    name: String,
+    #[serde(default)]
+    ignore: bool,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use paths::AbsPathBuf;
+
+    #[test]
+    fn test_failure_parse_extracts_relative_location_and_left_right() {
+        let stdout = "running 1 test\n\
+thread 'it_works' panicked at crates/foo/src/lib.rs:12:5:\n\
+assertion `left == right` failed\n\
+  left: 1\n\
+ right: 2\n\
+note: run with `RUST_BACKTRACE=1` environment variable to display a backtrace\n";
+
+        let failure = TestFailure::parse(stdout);
+
+        // workspace-relative, as printed by libtest; not an absolute path
+        assert_eq!(failure.location, Some(("crates/foo/src/lib.rs".to_owned(), 12, 5)));
+        assert_eq!(failure.left_right, Some(("1".to_owned(), "2".to_owned())));
+        assert_eq!(failure.message, "assertion `left == right` failed");
+
+        let root = AbsPathBuf::try_from("/workspace").unwrap();
+        let (file, row, col) = failure.resolved_location(&root).unwrap();
+        assert_eq!(file, root.join("crates/foo/src/lib.rs"));
+        assert_eq!((row, col), (12, 5));
+    }
+
+    #[test]
+    fn test_failure_parse_stops_message_at_blank_line() {
+        let stdout = "thread 'it_works' panicked at src/lib.rs:1:1:\n\
+oops\n\
+\n\
+this line belongs to the next test, not the message\n";
+
+        let failure = TestFailure::parse(stdout);
+        assert_eq!(failure.message, "oops");
+    }
+
+    #[test]
+    fn test_failure_parse_ignores_panics_after_the_first() {
+        let stdout = "thread 'it_works' panicked at src/lib.rs:1:1:\n\
+first panic message\n\
+thread 'other' panicked at src/other.rs:2:2:\n\
+second panic message\n";
+
+        let failure = TestFailure::parse(stdout);
+        assert_eq!(failure.location, Some(("src/lib.rs".to_owned(), 1, 1)));
+        assert_eq!(failure.message, "first panic message");
+    }
+
+    #[test]
+    fn test_failure_parse_skips_non_panic_thread_lines() {
+        // a `thread '...'` line that isn't actually a panic header (no `panicked at`)
+        // shouldn't be mistaken for one and block the real panic that follows
+        let stdout = "thread 'io-worker' waiting for shutdown\n\
+thread 'it_works' panicked at src/lib.rs:7:9:\n\
+real failure\n";
+
+        let failure = TestFailure::parse(stdout);
+        assert_eq!(failure.location, Some(("src/lib.rs".to_owned(), 7, 9)));
+        assert_eq!(failure.message, "real failure");
+    }
+
+    #[test]
+    fn parse_lcov_groups_lines_by_file() {
+        let lcov = "SF:src/lib.rs\nDA:1,3\nDA:2,0\nend_of_record\nSF:src/main.rs\nDA:10,1\nend_of_record\n";
+        let files = parse_lcov(lcov);
+        assert_eq!(
+            files,
+            vec![
+                ("src/lib.rs".to_owned(), vec![(1, 3), (2, 0)]),
+                ("src/main.rs".to_owned(), vec![(10, 1)]),
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_lcov_ignores_unknown_records_and_missing_end_of_record() {
+        let lcov = "SF:src/lib.rs\nFN:1,foo\nDA:1,3\nBRDA:1,0,0,1\n";
+        let files = parse_lcov(lcov);
+        assert_eq!(files, vec![("src/lib.rs".to_owned(), vec![(1, 3)])]);
+    }
+
+    #[test]
+    fn from_line_parses_nextest_list_summary_into_discovered() {
+        let line = r#"{"rust-suites":{"crate-a::tests":{"binary-path":"/target/debug/tests","package-id":"crate-a","kind":"test","testcases":{"smoke":{"ignored":false}}}}}"#;
+
+        let message = CargoTestMessage::from_line(line, &mut String::new()).unwrap();
+        match message {
+            CargoTestMessage::Discovered { suites } => {
+                assert_eq!(suites.len(), 1);
+                let (suite, tests) = &suites[0];
+                assert_eq!(suite, "crate-a::tests");
+                assert_eq!(tests.len(), 1);
+                assert_eq!(tests[0].name, "smoke");
+                assert!(!tests[0].ignored);
+                assert_eq!(tests[0].kind, "test");
+            }
+            other => panic!("expected Discovered, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn from_line_parses_libtest_list_entry_into_discovered() {
+        let line = r#"{"name":"it_works","ignore":true}"#;
+
+        let message = CargoTestMessage::from_line(line, &mut String::new()).unwrap();
+        match message {
+            CargoTestMessage::Discovered { suites } => {
+                assert_eq!(suites, vec![(
+                    "workspace".to_owned(),
+                    vec![DiscoveredTest {
+                        name: "it_works".to_owned(),
+                        ignored: true,
+                        kind: "test".to_owned(),
+                    }],
+                )]);
+            }
+            other => panic!("expected Discovered, got {other:?}"),
+        }
+    }
+
+    fn test_message(name: &str, state: TestState) -> CargoTestMessage {
+        CargoTestMessage::Test { name: name.to_owned(), state }
+    }
+
+    #[test]
+    fn flaky_aggregator_collapses_failure_then_pass_into_flaky() {
+        let mut aggregator = FlakyAggregator::default();
+
+        let failed = test_message(
+            "it_works",
+            TestState::Failed {
+                stdout: String::new(),
+                exec_time: None,
+                failure: TestFailure::default(),
+            },
+        );
+        assert!(aggregator.observe("crate-a", failed).is_none());
+
+        let ok = test_message("it_works", TestState::Ok { exec_time: None });
+        let forwarded = aggregator.observe("crate-a", ok).unwrap();
+        match forwarded {
+            CargoTestMessage::Test {
+                name,
+                state: TestState::Flaky { passed_on_attempt, total_attempts },
+            } => {
+                assert_eq!(name, "it_works");
+                assert_eq!(passed_on_attempt, 2);
+                assert_eq!(total_attempts, 2);
+            }
+            other => panic!("expected Flaky, got {other:?}"),
+        }
+
+        assert!(aggregator.flush().is_empty());
+    }
+
+    #[test]
+    fn flaky_aggregator_flushes_tests_that_failed_on_every_attempt() {
+        let mut aggregator = FlakyAggregator::default();
+
+        let failed = test_message(
+            "it_works",
+            TestState::Failed {
+                stdout: String::new(),
+                exec_time: None,
+                failure: TestFailure::default(),
+            },
+        );
+        assert!(aggregator.observe("crate-a", failed).is_none());
+
+        let flushed = aggregator.flush();
+        assert_eq!(flushed.len(), 1);
+        match &flushed[0] {
+            CargoTestMessage::Test { name, state: TestState::Failed { .. } } => {
+                assert_eq!(name, "it_works");
+            }
+            other => panic!("expected Failed, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn flaky_aggregator_passes_through_unrelated_messages() {
+        let mut aggregator = FlakyAggregator::default();
+        let ok = test_message("it_works", TestState::Ok { exec_time: Some(0.1) });
+        assert!(matches!(aggregator.observe("crate-a", ok), Some(CargoTestMessage::Test { .. })));
+    }
+
+    #[test]
+    fn flaky_aggregator_keys_by_suite_so_same_named_tests_dont_collide() {
+        let mut aggregator = FlakyAggregator::default();
+
+        // `smoke` fails every attempt in crate-a...
+        let failed_a = test_message(
+            "smoke",
+            TestState::Failed {
+                stdout: String::new(),
+                exec_time: None,
+                failure: TestFailure::default(),
+            },
+        );
+        assert!(aggregator.observe("crate-a", failed_a).is_none());
+
+        // ...while an unrelated `smoke` in crate-b passes outright
+        let ok_b = test_message("smoke", TestState::Ok { exec_time: None });
+        let forwarded = aggregator.observe("crate-b", ok_b).unwrap();
+        assert!(matches!(
+            forwarded,
+            CargoTestMessage::Test { state: TestState::Ok { .. }, .. }
+        ));
+
+        // crate-a's failure must still be pending, not wiped out by crate-b's pass
+        let flushed = aggregator.flush();
+        assert_eq!(flushed.len(), 1);
+        assert!(matches!(
+            &flushed[0],
+            CargoTestMessage::Test { state: TestState::Failed { .. }, .. }
+        ));
+    }
 }